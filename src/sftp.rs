@@ -1,15 +1,59 @@
-use std::io::{self, Read, Write};
+use std::future::Future;
+use std::io::{self, Read, Seek, Write};
+use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
 
 use async_io::Async;
-use futures_io::{AsyncRead, AsyncWrite};
+use bytes::Bytes;
+use futures_core::Stream;
+use futures_io::{AsyncRead, AsyncSeek, AsyncWrite};
+use futures_util::{AsyncReadExt, AsyncWriteExt, StreamExt, TryStreamExt};
 use ssh2::{File, FileStat, OpenFlags, OpenType, RenameFlags, Sftp};
 
 use crate::util::poll_once;
 
+fn checked_seek_offset(base: u64, delta: i64) -> io::Result<u64> {
+    let new_offset = if delta >= 0 {
+        base.checked_add(delta as u64)
+    } else {
+        base.checked_sub(delta.unsigned_abs())
+    };
+
+    new_offset.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid seek to a negative or overflowing position",
+        )
+    })
+}
+
+// POSIX file-type bits within `FileStat::perm`, used to tell symlinks apart
+// from regular files/directories when walking a remote tree.
+const S_IFMT: u32 = 0o170_000;
+const S_IFLNK: u32 = 0o120_000;
+
+fn is_symlink_stat(stat: &FileStat) -> bool {
+    stat.perm
+        .map(|perm| perm & S_IFMT == S_IFLNK)
+        .unwrap_or(false)
+}
+
+// libssh2 signals end-of-directory from a raw `sftp_readdir` call with this
+// status rather than a handle/protocol error; `Sftp::readdir` already treats
+// it as a normal stop condition instead of a real error.
+const LIBSSH2_ERROR_FILE: i32 = -16;
+
+fn is_eof_error(err: &ssh2::Error) -> bool {
+    matches!(err.code(), ssh2::ErrorCode::Session(LIBSSH2_ERROR_FILE))
+}
+
+fn is_dot_or_dotdot(name: &Path) -> bool {
+    name == Path::new(".") || name == Path::new("..")
+}
+
 pub struct AsyncSftp<S> {
     inner: Sftp,
     async_io: Arc<Async<S>>,
@@ -84,6 +128,14 @@ impl<S> AsyncSftp<S> {
             .await
     }
 
+    pub fn read_dir_stream(&self, dirname: &Path) -> ReadDirStream<'_, S> {
+        ReadDirStream {
+            sftp: self,
+            dirname: dirname.to_path_buf(),
+            state: ReadDirStreamState::Opening,
+        }
+    }
+
     pub async fn mkdir(&self, filename: &Path, mode: i32) -> io::Result<()> {
         let inner = &self.inner;
 
@@ -172,6 +224,122 @@ impl<S> AsyncSftp<S> {
             .write_with(|_| inner.unlink(file).map_err(|err| err.into()))
             .await
     }
+
+    pub fn upload_dir<'a>(
+        &'a self,
+        local: &'a Path,
+        remote: &'a Path,
+    ) -> Pin<Box<dyn Future<Output = io::Result<()>> + 'a>> {
+        Box::pin(async move {
+            let local_metadata = async_fs::symlink_metadata(local).await?;
+
+            self.mkdir(remote, local_metadata.permissions().mode() as i32)
+                .await
+                .or_else(|err| match err.kind() {
+                    io::ErrorKind::AlreadyExists => Ok(()),
+                    _ => Err(err),
+                })?;
+
+            let mut entries = async_fs::read_dir(local).await?;
+
+            while let Some(entry) = entries.try_next().await? {
+                let file_type = entry.file_type().await?;
+                let local_path = entry.path();
+                let remote_path = remote.join(entry.file_name());
+
+                if file_type.is_symlink() {
+                    let target = async_fs::read_link(&local_path).await?;
+
+                    self.symlink(&remote_path, &target).await?;
+                } else if file_type.is_dir() {
+                    self.upload_dir(&local_path, &remote_path).await?;
+                } else {
+                    let metadata = entry.metadata().await?;
+                    let mut local_file = async_fs::File::open(&local_path).await?;
+                    let mut remote_file = self.create(&remote_path).await?;
+
+                    // `write_from` flushes and closes `remote_file` once the
+                    // copy is done, so the mode has to be set before it runs.
+                    remote_file
+                        .setstat(FileStat {
+                            size: None,
+                            uid: None,
+                            gid: None,
+                            perm: Some(metadata.permissions().mode()),
+                            atime: None,
+                            mtime: None,
+                        })
+                        .await?;
+                    remote_file.write_from(&mut local_file).await?;
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    pub fn download_dir<'a>(
+        &'a self,
+        remote: &'a Path,
+        local: &'a Path,
+    ) -> Pin<Box<dyn Future<Output = io::Result<()>> + 'a>> {
+        Box::pin(async move {
+            async_fs::create_dir_all(local).await?;
+
+            let mut entries = self.read_dir_stream(remote);
+
+            while let Some(entry) = entries.try_next().await? {
+                let (remote_path, stat) = entry;
+                let local_path = local.join(remote_path.file_name().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "remote entry has no file name")
+                })?);
+
+                if is_symlink_stat(&stat) {
+                    let target = self.readlink(&remote_path).await?;
+
+                    #[cfg(unix)]
+                    async_fs::unix::symlink(&target, &local_path).await?;
+                } else if stat.is_dir() {
+                    self.download_dir(&remote_path, &local_path).await?;
+                } else {
+                    let mut remote_file = self.open(&remote_path).await?;
+                    let mut local_file = async_fs::File::create(&local_path).await?;
+
+                    remote_file.read_to(&mut local_file).await?;
+                    remote_file.close().await?;
+
+                    if let Some(perm) = stat.perm {
+                        async_fs::set_permissions(
+                            &local_path,
+                            std::fs::Permissions::from_mode(perm),
+                        )
+                        .await?;
+                    }
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    pub fn remove_dir_all<'a>(
+        &'a self,
+        dir: &'a Path,
+    ) -> Pin<Box<dyn Future<Output = io::Result<()>> + 'a>> {
+        Box::pin(async move {
+            let mut entries = self.read_dir_stream(dir);
+
+            while let Some((path, stat)) = entries.try_next().await? {
+                if !is_symlink_stat(&stat) && stat.is_dir() {
+                    self.remove_dir_all(&path).await?;
+                } else {
+                    self.unlink(&path).await?;
+                }
+            }
+
+            self.rmdir(dir).await
+        })
+    }
 }
 
 //
@@ -180,11 +348,92 @@ impl<S> AsyncSftp<S> {
 pub struct AsyncFile<S> {
     inner: File,
     async_io: Arc<Async<S>>,
+    closed: bool,
 }
 
 impl<S> AsyncFile<S> {
     pub(crate) fn from_parts(inner: File, async_io: Arc<Async<S>>) -> Self {
-        Self { inner, async_io }
+        Self {
+            inner,
+            async_io,
+            closed: false,
+        }
+    }
+
+    fn poll_readdir(&mut self, cx: &mut Context) -> Poll<Option<io::Result<(PathBuf, FileStat)>>> {
+        let inner = &mut self.inner;
+
+        let ret = poll_once(
+            cx,
+            self.async_io.read_with(|_| match inner.readdir() {
+                Ok(entry) => Ok(Some(entry)),
+                Err(ref err) if is_eof_error(err) => Ok(None),
+                Err(err) => Err(err.into()),
+            }),
+        );
+
+        ret.map(|ret: io::Result<Option<(PathBuf, FileStat)>>| ret.transpose())
+    }
+
+    pub async fn stat(&mut self) -> io::Result<FileStat> {
+        let inner = &mut self.inner;
+
+        self.async_io
+            .write_with(|_| inner.stat().map_err(|err| err.into()))
+            .await
+    }
+
+    pub async fn setstat(&mut self, stat: FileStat) -> io::Result<()> {
+        let inner = &mut self.inner;
+
+        self.async_io
+            .write_with(|_| inner.setstat(stat.clone()).map_err(|err| err.into()))
+            .await
+    }
+
+    pub async fn write_from<R: AsyncRead + Unpin>(&mut self, mut reader: R) -> io::Result<()> {
+        let mut buf = [0u8; 8 * 1024];
+
+        loop {
+            let n = reader.read(&mut buf).await?;
+
+            if n == 0 {
+                break;
+            }
+
+            self.write_all(&buf[..n]).await?;
+        }
+
+        self.flush().await?;
+        self.close().await
+    }
+
+    pub async fn read_to<W: AsyncWrite + Unpin>(&mut self, mut writer: W) -> io::Result<()> {
+        let mut buf = [0u8; 8 * 1024];
+
+        loop {
+            let n = self.read(&mut buf).await?;
+
+            if n == 0 {
+                break;
+            }
+
+            writer.write_all(&buf[..n]).await?;
+        }
+
+        writer.flush().await
+    }
+
+    pub async fn write_from_stream<St>(&mut self, mut stream: St) -> io::Result<()>
+    where
+        St: Stream<Item = io::Result<Bytes>> + Unpin,
+    {
+        while let Some(chunk) = stream.next().await {
+            self.write_all(&chunk?).await?;
+        }
+
+        self.flush().await?;
+        self.close().await
     }
 }
 
@@ -222,9 +471,170 @@ impl<S> AsyncWrite for AsyncFile<S> {
     fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
         let this = self.get_mut();
 
-        let _ = &mut this.inner;
+        if this.closed {
+            return Poll::Ready(Ok(()));
+        }
+
+        let inner = &mut this.inner;
+
+        let poll = poll_once(
+            cx,
+            this.async_io.write_with(|_| {
+                inner.flush()?;
+                inner.close().map_err(|err| err.into())
+            }),
+        );
+
+        if let Poll::Ready(Ok(())) = &poll {
+            this.closed = true;
+        }
+
+        poll
+    }
+}
+
+impl<S> AsyncSeek for AsyncFile<S> {
+    fn poll_seek(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        pos: io::SeekFrom,
+    ) -> Poll<io::Result<u64>> {
+        let this = self.get_mut();
+
+        let inner = &mut this.inner;
+
+        poll_once(
+            cx,
+            this.async_io.write_with(|_| {
+                let offset = match pos {
+                    io::SeekFrom::Start(offset) => offset,
+                    io::SeekFrom::Current(offset) => {
+                        let current = inner.seek(io::SeekFrom::Current(0))?;
+
+                        checked_seek_offset(current, offset)?
+                    }
+                    io::SeekFrom::End(offset) => {
+                        let size = inner.stat().map_err(|err| err.into())?.size.unwrap_or(0);
+
+                        checked_seek_offset(size, offset)?
+                    }
+                };
+
+                inner.seek(io::SeekFrom::Start(offset))
+            }),
+        )
+    }
+}
+
+enum ReadDirStreamState<S> {
+    Opening,
+    Open(AsyncFile<S>),
+    // End of the listing (or a readdir error) was hit; finish closing the
+    // directory handle server-side before handing `queued` back.
+    Closing(AsyncFile<S>, Option<io::Result<(PathBuf, FileStat)>>),
+    Done,
+}
+
+pub struct ReadDirStream<'a, S> {
+    sftp: &'a AsyncSftp<S>,
+    dirname: PathBuf,
+    state: ReadDirStreamState<S>,
+}
+
+impl<'a, S> Stream for ReadDirStream<'a, S> {
+    type Item = io::Result<(PathBuf, FileStat)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            match &mut this.state {
+                ReadDirStreamState::Opening => {
+                    let inner = &this.sftp.inner;
+                    let dirname = &this.dirname;
+
+                    let ret = poll_once(
+                        cx,
+                        this.sftp
+                            .async_io
+                            .write_with(|_| inner.opendir(dirname).map_err(|err| err.into())),
+                    );
+
+                    match ret {
+                        Poll::Ready(Ok(file)) => {
+                            this.state = ReadDirStreamState::Open(AsyncFile::from_parts(
+                                file,
+                                this.sftp.async_io.clone(),
+                            ));
+                        }
+                        Poll::Ready(Err(err)) => {
+                            this.state = ReadDirStreamState::Done;
+                            return Poll::Ready(Some(Err(err)));
+                        }
+                        Poll::Pending => return Poll::Pending,
+                    }
+                }
+                ReadDirStreamState::Open(file) => match file.poll_readdir(cx) {
+                    Poll::Ready(Some(Ok((name, stat)))) => {
+                        if is_dot_or_dotdot(&name) {
+                            continue;
+                        }
+
+                        // Match `Sftp::readdir`, which hands back entries
+                        // joined onto the directory being listed rather than
+                        // bare file names.
+                        return Poll::Ready(Some(Ok((this.dirname.join(&name), stat))));
+                    }
+                    Poll::Ready(Some(Err(err))) => {
+                        let file = match std::mem::replace(&mut this.state, ReadDirStreamState::Done) {
+                            ReadDirStreamState::Open(file) => file,
+                            _ => unreachable!(),
+                        };
+
+                        this.state = ReadDirStreamState::Closing(file, Some(Err(err)));
+                    }
+                    Poll::Ready(None) => {
+                        let file = match std::mem::replace(&mut this.state, ReadDirStreamState::Done) {
+                            ReadDirStreamState::Open(file) => file,
+                            _ => unreachable!(),
+                        };
+
+                        this.state = ReadDirStreamState::Closing(file, None);
+                    }
+                    Poll::Pending => return Poll::Pending,
+                },
+                ReadDirStreamState::Closing(file, queued) => {
+                    match Pin::new(file).poll_close(cx) {
+                        Poll::Ready(close_result) => {
+                            let item = queued.take().or_else(|| close_result.err().map(Err));
+
+                            this.state = ReadDirStreamState::Done;
+
+                            return Poll::Ready(item);
+                        }
+                        Poll::Pending => return Poll::Pending,
+                    }
+                }
+                ReadDirStreamState::Done => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::checked_seek_offset;
+
+    #[test]
+    fn checked_seek_offset_applies_positive_and_negative_deltas() {
+        assert_eq!(checked_seek_offset(10, 5).unwrap(), 15);
+        assert_eq!(checked_seek_offset(10, -5).unwrap(), 5);
+        assert_eq!(checked_seek_offset(0, 0).unwrap(), 0);
+    }
 
-        // TODO
-        poll_once(cx, this.async_io.write_with(|_| Ok(())))
+    #[test]
+    fn checked_seek_offset_rejects_negative_and_overflowing_results() {
+        assert!(checked_seek_offset(0, -1).is_err());
+        assert!(checked_seek_offset(u64::MAX, 1).is_err());
     }
 }